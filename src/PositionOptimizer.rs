@@ -7,51 +7,77 @@ use alloc::vec::Vec;
 use stylus_sdk::{
     alloy_primitives::{Address, U256, I256},
     alloy_sol_types::{sol, SolError},
+    call::Call,
     evm, msg,
     prelude::*,
 };
 
+#[path = "tick_math.rs"]
+mod tick_math;
+use tick_math::{sqrt_price_to_tick, tick_to_sqrt_price_x96, MAX_TICK, MIN_TICK};
+
+// Minimal interface onto a Uniswap V3-style pool's built-in TWAP oracle.
+sol_interface! {
+    interface IUniswapV3PoolOracle {
+        function observe(uint32[] calldata secondsAgos)
+            external
+            view
+            returns (int56[] memory tickCumulatives, uint160[] memory secondsPerLiquidityCumulativeX128s);
+    }
+}
+
 // Helper function to compute mean of prices
-fn compute_mean(prices: &[U256]) -> U256 {
+fn compute_mean(prices: &[U256]) -> Result<U256, SolError> {
     let mut sum = U256::ZERO;
     let len = prices.len();
-    
+
     if len == 0 {
-        return U256::ZERO;
+        return Ok(U256::ZERO);
     }
-    
+
     for price in prices {
-        sum = sum.saturating_add(*price);
+        sum = sum.checked_add(*price).ok_or(MathOverflow {})?;
     }
-    
-    sum / U256::from(len)
+
+    Ok(sum / U256::from(len))
 }
 
-// Helper function to compute standard deviation
-fn compute_std_dev(prices: &[U256], mean: U256) -> U256 {
+// Compute variance with Welford's single-pass algorithm: a running mean and
+// sum-of-squares (M2), built with checked arithmetic so an overflow returns
+// MathOverflow instead of silently clamping as `saturating_mul`/`_add` did.
+fn compute_variance(prices: &[U256]) -> Result<U256, SolError> {
     let len = prices.len();
-    
+
     if len <= 1 {
-        return U256::ZERO;
+        return Ok(U256::ZERO);
     }
-    
-    let mut sum_squared_diff = U256::ZERO;
-    
-    for price in prices {
-        let diff = if *price >= mean {
-            *price - mean
-        } else {
-            mean - *price
-        };
-        
-        // Square the difference (handle overflow)
-        let squared = diff.saturating_mul(diff);
-        sum_squared_diff = sum_squared_diff.saturating_add(squared);
+
+    let mut mean = I256::ZERO;
+    let mut m2 = I256::ZERO;
+
+    for (i, price) in prices.iter().enumerate() {
+        let n = I256::try_from(i + 1).map_err(|_| MathOverflow {})?;
+        let x = I256::try_from(*price).map_err(|_| MathOverflow {})?;
+
+        let delta = x.checked_sub(mean).ok_or(MathOverflow {})?;
+        let delta_over_n = delta.checked_div(n).ok_or(MathOverflow {})?;
+        mean = mean.checked_add(delta_over_n).ok_or(MathOverflow {})?;
+
+        let delta2 = x.checked_sub(mean).ok_or(MathOverflow {})?;
+        let term = delta.checked_mul(delta2).ok_or(MathOverflow {})?;
+        m2 = m2.checked_add(term).ok_or(MathOverflow {})?;
     }
-    
-    // Calculate standard deviation (sqrt of variance)
-    let variance = sum_squared_diff / U256::from(len);
-    sqrt(variance)
+
+    let len = I256::try_from(len).map_err(|_| MathOverflow {})?;
+    let variance = m2.checked_div(len).ok_or(MathOverflow {})?;
+
+    U256::try_from(variance).map_err(|_| MathOverflow {}.into())
+}
+
+// Helper function to compute standard deviation
+fn compute_std_dev(prices: &[U256], _mean: U256) -> Result<U256, SolError> {
+    let variance = compute_variance(prices)?;
+    Ok(sqrt(variance))
 }
 
 // Helper function to compute square root
@@ -71,13 +97,14 @@ fn sqrt(n: U256) -> U256 {
     x
 }
 
-// Helper function to convert from price to tick (simplified)
-fn price_to_tick(price: U256) -> i32 {
-    // This is a very simplified version. In a real implementation,
-    // we would use TickMath's logic to convert from price to tick.
-    let price_f = price.as_u128() as f64;
-    let tick_f = (price_f.ln() / 1.0001f64.ln()) as i32;
-    tick_f
+// Helper function to convert a Q64.96 sqrt price (e.g. one returned by
+// `get_twap_prices`) to its tick. Rejects inputs outside the sqrt-price
+// range a valid tick can represent, so a raw (non-sqrt-price-encoded) price
+// passed in by mistake is caught here when it falls below MIN_SQRT_RATIO
+// instead of silently resolving to a tick near the valid range's edge; a
+// raw price inside the sqrt-price range is not detected.
+fn price_to_tick(sqrt_price_x96: U256) -> Result<i32, SolError> {
+    sqrt_price_to_tick(sqrt_price_x96).map_err(|_| InvalidTickSpacing {}.into())
 }
 
 // Helper function to ensure tick is divisible by spacing
@@ -85,12 +112,255 @@ fn round_to_spacing(tick: i32, spacing: i32) -> i32 {
     (tick / spacing) * spacing
 }
 
+// Core of `calculate_optimal_position_bounds`, pulled out as a free function
+// (it doesn't touch contract storage) so it can be unit tested directly.
+fn optimal_position_bounds(recent_prices: &[U256]) -> Result<(i32, i32), SolError> {
+    if recent_prices.len() < 2 {
+        return Err(InvalidPriceArray {}.into());
+    }
+
+    // Calculate price statistics
+    let mean_price = compute_mean(recent_prices)?;
+    let std_dev = compute_std_dev(recent_prices, mean_price)?;
+
+    // Convert mean price to a tick
+    let mean_tick = price_to_tick(mean_price)?;
+
+    // Calculate standard deviation as a percentage of the mean price
+    let std_dev_percentage = if mean_price > U256::ZERO {
+        std_dev.checked_mul(U256::from(100)).ok_or(MathOverflow {})? / mean_price
+    } else {
+        U256::ZERO
+    };
+
+    // Calculate tick range based on volatility
+    // Higher volatility = wider range
+    let tick_spacing = 60; // Default tick spacing
+    let volatility_multiplier = if std_dev_percentage < U256::from(5) {
+        // Low volatility: tighter range
+        20
+    } else if std_dev_percentage < U256::from(10) {
+        // Medium volatility
+        30
+    } else if std_dev_percentage < U256::from(20) {
+        // High volatility
+        50
+    } else {
+        // Very high volatility: wide range
+        100
+    };
+
+    // Calculate the tick range
+    let tick_range = volatility_multiplier * tick_spacing;
+
+    // Calculate lower and upper ticks
+    let lower_tick = round_to_spacing(mean_tick - tick_range, tick_spacing);
+    let upper_tick = round_to_spacing(mean_tick + tick_range, tick_spacing);
+
+    Ok((lower_tick, upper_tick))
+}
+
+// Core of `calculate_liquidity_distribution`, pulled out as a free function
+// (it doesn't touch contract storage) so it can be unit tested directly.
+fn liquidity_distribution(
+    recent_prices: &[U256],
+    liquidity_amount: U256,
+    num_bins: u32,
+    shape: u8
+) -> Result<Vec<(i32, i32, U256)>, SolError> {
+    if num_bins == 0 {
+        return Err(InvalidTickSpacing {}.into());
+    }
+    if shape != SHAPE_FLAT && shape != SHAPE_TRIANGULAR {
+        return Err(InvalidDistributionShape {}.into());
+    }
+
+    let (lower_tick, upper_tick) = optimal_position_bounds(recent_prices)?;
+
+    let tick_spacing = 60; // Default tick spacing, matches optimal_position_bounds
+    let bin_width = round_to_spacing(
+        ((upper_tick - lower_tick) / num_bins as i32).max(tick_spacing),
+        tick_spacing
+    );
+
+    // Locate the bin that currently holds the mean price so the
+    // triangular shape can center its peak weight there.
+    let mean_price = compute_mean(recent_prices)?;
+    let mean_tick = price_to_tick(mean_price)?;
+
+    let mut center_bin = 0usize;
+    let mut bin_lower = lower_tick;
+    for j in 0..num_bins as usize {
+        let bin_upper = bin_lower + bin_width;
+        if mean_tick >= bin_lower && mean_tick < bin_upper {
+            center_bin = j;
+        }
+        bin_lower = bin_upper;
+    }
+
+    // w_j = num_bins - |j - center| for the tent shape, 1 for flat;
+    // normalizing by their sum keeps total liquidity unchanged.
+    let weights: Vec<u64> = (0..num_bins as usize)
+        .map(|j| {
+            if shape == SHAPE_TRIANGULAR {
+                let distance = (j as i64 - center_bin as i64).unsigned_abs();
+                (num_bins as u64).saturating_sub(distance)
+            } else {
+                1
+            }
+        })
+        .collect();
+    let weight_sum: u64 = weights.iter().sum();
+
+    let mut distribution = Vec::with_capacity(num_bins as usize);
+    let mut bin_lower = lower_tick;
+    let mut allocated = U256::ZERO;
+    for (j, weight) in weights.iter().enumerate() {
+        let bin_upper = bin_lower + bin_width;
+
+        // The last bin takes whatever liquidity remains so rounding
+        // from integer division never loses or duplicates liquidity.
+        let bin_liquidity = if j + 1 == weights.len() {
+            liquidity_amount - allocated
+        } else {
+            let share = liquidity_amount.checked_mul(U256::from(*weight)).ok_or(MathOverflow {})? / U256::from(weight_sum);
+            allocated += share;
+            share
+        };
+
+        distribution.push((bin_lower, bin_upper, bin_liquidity));
+        bin_lower = bin_upper;
+    }
+
+    Ok(distribution)
+}
+
+// Core of `should_rebalance`, pulled out as a free function (it doesn't
+// touch contract storage) so it can be unit tested directly.
+fn rebalance_check(
+    current_lower_tick: i32,
+    current_upper_tick: i32,
+    recent_prices: &[U256]
+) -> Result<(bool, i32, i32), SolError> {
+    // Calculate optimal bounds based on current conditions
+    let (optimal_lower, optimal_upper) = optimal_position_bounds(recent_prices)?;
+
+    // Check if price is outside the current range or close to the edge
+    let current_price = recent_prices[recent_prices.len() - 1];
+    let current_tick = price_to_tick(current_price)?;
+
+    // Calculate how far the current price is from the bounds (as a percentage of the range)
+    let current_range = current_upper_tick - current_lower_tick;
+
+    if current_range <= 0 {
+        return Err(InvalidTickSpacing {}.into());
+    }
+
+    // Calculate distance from bounds as percentage of range
+    let dist_from_lower = current_tick - current_lower_tick;
+    let dist_from_upper = current_upper_tick - current_tick;
+
+    let lower_pct = (dist_from_lower * 100) / current_range;
+    let upper_pct = (dist_from_upper * 100) / current_range;
+
+    // Determine if rebalancing is needed
+    let should_rebalance =
+        // Price outside range
+        current_tick <= current_lower_tick ||
+        current_tick >= current_upper_tick ||
+        // Price close to edge (less than 10% from edge)
+        lower_pct < 10 ||
+        upper_pct < 10 ||
+        // Optimal range is significantly different
+        (optimal_lower - current_lower_tick).abs() > (current_range / 4) ||
+        (optimal_upper - current_upper_tick).abs() > (current_range / 4);
+
+    Ok((should_rebalance, optimal_lower, optimal_upper))
+}
+
+// Core of `simulate_swap`, pulled out as a free function (it doesn't touch
+// contract storage) so it can be unit tested directly.
+fn swap_simulation(
+    reserve_in: U256,
+    reserve_out: U256,
+    amount_in: U256,
+    fee_bps: u32
+) -> Result<(U256, U256, U256), SolError> {
+    if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+        return Err(InvalidReserves {}.into());
+    }
+    if fee_bps > 10000 {
+        return Err(InvalidFee {}.into());
+    }
+
+    let scale = U256::from(10000);
+    let fee = U256::from(fee_bps);
+
+    let old_price = reserve_out.checked_mul(scale).ok_or(MathOverflow {})? / reserve_in;
+
+    let fee_multiplier = scale.checked_sub(fee).ok_or(MathOverflow {})?;
+    let amount_in_after_fee = amount_in.checked_mul(fee_multiplier).ok_or(MathOverflow {})? / scale;
+
+    let new_reserve_in = reserve_in.checked_add(amount_in_after_fee).ok_or(MathOverflow {})?;
+    let amount_out = reserve_out.checked_mul(amount_in_after_fee).ok_or(MathOverflow {})? / new_reserve_in;
+
+    let new_reserve_out = reserve_out.checked_sub(amount_out).ok_or(MathOverflow {})?;
+    let new_price = new_reserve_out.checked_mul(scale).ok_or(MathOverflow {})? / new_reserve_in;
+
+    let price_diff = if new_price >= old_price {
+        new_price - old_price
+    } else {
+        old_price - new_price
+    };
+    let price_impact_bps = price_diff.checked_mul(scale).ok_or(MathOverflow {})? / old_price;
+
+    Ok((amount_out, new_price, price_impact_bps))
+}
+
+// Core of `fee_for_target_impact`, pulled out as a free function (it doesn't
+// touch contract storage) so it can be unit tested directly.
+fn fee_for_impact(
+    reserve_in: U256,
+    reserve_out: U256,
+    amount_in: U256,
+    target_impact_bps: U256
+) -> Result<u32, SolError> {
+    if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+        return Err(InvalidReserves {}.into());
+    }
+
+    let mut lower: u32 = 0;
+    let mut upper: u32 = 10000;
+
+    while lower < upper {
+        let mid = lower + (upper - lower) / 2;
+        let (_, _, impact) = swap_simulation(reserve_in, reserve_out, amount_in, mid)?;
+
+        if impact <= target_impact_bps {
+            upper = mid;
+        } else {
+            lower = mid + 1;
+        }
+    }
+
+    Ok(lower)
+}
+
 // Contract errors
 sol! {
     error InvalidPriceArray();
     error InvalidTickSpacing();
+    error InvalidDistributionShape();
+    error OracleCallFailed();
+    error MathOverflow();
+    error InvalidReserves();
+    error InvalidFee();
 }
 
+// `shape` values accepted by `calculate_liquidity_distribution`.
+const SHAPE_FLAT: u8 = 0;
+const SHAPE_TRIANGULAR: u8 = 1;
+
 #[solidity_storage]
 struct PositionOptimizer {
     // Scaling factor for precision
@@ -102,11 +372,40 @@ impl PositionOptimizer {
     pub fn constructor(&mut self) {
         self.scaling_factor = U256::from(10000);
     }
-    
+
+    /// Read a TWAP price series straight from a pool's oracle instead of
+    /// trusting a caller-supplied price array. `seconds_ago` must be sorted
+    /// oldest-first (e.g. `[120, 90, 60, 30, 0]`); each consecutive pair
+    /// becomes one arithmetic-mean-tick price, time-weighted and therefore
+    /// far harder to manipulate than a spot price.
+    /// @param pool Address of the Uniswap V3-style pool exposing `observe`
+    /// @param seconds_ago Lookback offsets in seconds, oldest-first
+    /// @return One price per window, suitable for `calculate_volatility_score`
+    ///   and `calculate_optimal_position_bounds`
+    pub fn get_twap_prices(
+        &mut self,
+        pool: Address,
+        seconds_ago: Vec<u32>
+    ) -> Result<Vec<U256>, SolError> {
+        if seconds_ago.len() < 2 {
+            return Err(InvalidPriceArray {}.into());
+        }
+
+        let oracle = IUniswapV3PoolOracle::new(pool);
+        let config = Call::new_in(self);
+        let (tick_cumulatives, _) = oracle
+            .observe(config, seconds_ago.clone())
+            .map_err(|_| OracleCallFailed {})?;
+
+        let tick_cumulatives: Vec<i64> = tick_cumulatives.iter().map(|t| t.as_i64()).collect();
+        tick_math::twap_from_tick_cumulatives(&tick_cumulatives, &seconds_ago)
+            .map_err(|_| OracleCallFailed {}.into())
+    }
+
     /// Calculate the optimal position bounds for a liquidity position
     /// @param token0 Address of the first token
     /// @param token1 Address of the second token
-    /// @param recent_prices Array of recent prices
+    /// @param recent_prices Array of recent Q64.96 sqrt prices (e.g. from `get_twap_prices`)
     /// @param liquidity_amount Amount of liquidity to provide
     /// @return Lower and upper tick bounds
     pub fn calculate_optimal_position_bounds(
@@ -116,108 +415,46 @@ impl PositionOptimizer {
         recent_prices: Vec<U256>,
         _liquidity_amount: U256
     ) -> Result<(i32, i32), SolError> {
-        // Validate inputs
-        if recent_prices.len() < 2 {
-            return Err(InvalidPriceArray {}.into());
-        }
-        
-        // Calculate price statistics
-        let mean_price = compute_mean(&recent_prices);
-        let std_dev = compute_std_dev(&recent_prices, mean_price);
-        
-        // Convert mean price to a tick
-        let mean_tick = price_to_tick(mean_price);
-        
-        // Calculate standard deviation as a percentage of the mean price
-        let std_dev_percentage = if mean_price > U256::ZERO {
-            (std_dev * U256::from(100)) / mean_price
-        } else {
-            U256::ZERO
-        };
-        
-        // Calculate tick range based on volatility
-        // Higher volatility = wider range
-        let tick_spacing = 60; // Default tick spacing
-        let volatility_multiplier = if std_dev_percentage < U256::from(5) {
-            // Low volatility: tighter range
-            20
-        } else if std_dev_percentage < U256::from(10) {
-            // Medium volatility
-            30
-        } else if std_dev_percentage < U256::from(20) {
-            // High volatility
-            50
-        } else {
-            // Very high volatility: wide range
-            100
-        };
-        
-        // Calculate the tick range
-        let tick_range = volatility_multiplier * tick_spacing;
-        
-        // Calculate lower and upper ticks
-        let lower_tick = round_to_spacing(mean_tick - tick_range, tick_spacing);
-        let upper_tick = round_to_spacing(mean_tick + tick_range, tick_spacing);
-        
-        Ok((lower_tick, upper_tick))
+        optimal_position_bounds(&recent_prices)
     }
-    
+
+    /// Split the volatility-derived range into `num_bins` tick segments and
+    /// assign each one a share of `liquidity_amount`.
+    /// @param recent_prices Array of recent Q64.96 sqrt prices (e.g. from `get_twap_prices`)
+    /// @param liquidity_amount Total liquidity to distribute across bins
+    /// @param num_bins Number of tick segments to split the range into
+    /// @param shape Distribution shape: `SHAPE_FLAT` (equal per bin) or `SHAPE_TRIANGULAR`
+    ///   (peaks at the bin containing the mean price, tapering linearly to the edges)
+    /// @return One `(lower_tick, upper_tick, liquidity)` tuple per bin, in ascending tick order
+    pub fn calculate_liquidity_distribution(
+        &self,
+        recent_prices: Vec<U256>,
+        liquidity_amount: U256,
+        num_bins: u32,
+        shape: u8
+    ) -> Result<Vec<(i32, i32, U256)>, SolError> {
+        liquidity_distribution(&recent_prices, liquidity_amount, num_bins, shape)
+    }
+
     /// Determine if a position should be rebalanced based on current market conditions
     /// @param token0 Address of the first token
     /// @param token1 Address of the second token
     /// @param current_lower_tick Current lower tick bound
     /// @param current_upper_tick Current upper tick bound
-    /// @param recent_prices Array of recent prices
+    /// @param recent_prices Array of recent Q64.96 sqrt prices (e.g. from `get_twap_prices`)
     /// @return (should_rebalance, new_lower_tick, new_upper_tick)
     pub fn should_rebalance(
         &self,
-        token0: Address,
-        token1: Address,
+        _token0: Address,
+        _token1: Address,
         current_lower_tick: i32,
         current_upper_tick: i32,
         recent_prices: Vec<U256>
     ) -> Result<(bool, i32, i32), SolError> {
-        // Calculate optimal bounds based on current conditions
-        let (optimal_lower, optimal_upper) = self.calculate_optimal_position_bounds(
-            token0,
-            token1,
-            recent_prices,
-            U256::ZERO // Not relevant for this calculation
-        )?;
-        
-        // Check if price is outside the current range or close to the edge
-        let current_price = recent_prices[recent_prices.len() - 1];
-        let current_tick = price_to_tick(current_price);
-        
-        // Calculate how far the current price is from the bounds (as a percentage of the range)
-        let current_range = current_upper_tick - current_lower_tick;
-        
-        if current_range <= 0 {
-            return Err(InvalidTickSpacing {}.into());
-        }
-        
-        // Calculate distance from bounds as percentage of range
-        let dist_from_lower = current_tick - current_lower_tick;
-        let dist_from_upper = current_upper_tick - current_tick;
-        
-        let lower_pct = (dist_from_lower * 100) / current_range;
-        let upper_pct = (dist_from_upper * 100) / current_range;
-        
-        // Determine if rebalancing is needed
-        let should_rebalance = 
-            // Price outside range
-            current_tick <= current_lower_tick || 
-            current_tick >= current_upper_tick ||
-            // Price close to edge (less than 10% from edge)
-            lower_pct < 10 || 
-            upper_pct < 10 ||
-            // Optimal range is significantly different
-            (optimal_lower - current_lower_tick).abs() > (current_range / 4) ||
-            (optimal_upper - current_upper_tick).abs() > (current_range / 4);
-        
-        Ok((should_rebalance, optimal_lower, optimal_upper))
+        rebalance_check(current_lower_tick, current_upper_tick, &recent_prices)
     }
-    
+
+
     /// Calculate the capital efficiency of a position
     /// @param current_lower_tick Current lower tick bound
     /// @param current_upper_tick Current upper tick bound
@@ -251,7 +488,121 @@ impl PositionOptimizer {
         }
         
         let efficiency = 100 - ((distance_from_middle * 100) / max_distance) as u32;
-        
+
         Ok(efficiency)
     }
-} 
\ No newline at end of file
+
+    /// Simulate a constant-product swap to see how it would move price,
+    /// so fee setting can be priced against realized slippage rather than
+    /// volatility alone.
+    /// @param reserve_in Pool reserves of the input token before the swap
+    /// @param reserve_out Pool reserves of the output token before the swap
+    /// @param amount_in Amount of the input token being swapped
+    /// @param fee_bps Swap fee in basis points, deducted from `amount_in` before the trade
+    /// @return (amount_out, new_price, price_impact_bps): amount_out in units
+    ///   of the output token, new_price as reserve_out / reserve_in scaled by
+    ///   10000, and the price impact in basis points
+    pub fn simulate_swap(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+        fee_bps: u32
+    ) -> Result<(U256, U256, U256), SolError> {
+        swap_simulation(reserve_in, reserve_out, amount_in, fee_bps)
+    }
+
+    /// Invert `simulate_swap` to find the smallest fee that keeps a trade's
+    /// price impact at or below `target_impact_bps`. Impact is monotonically
+    /// non-increasing in the fee (a higher fee shrinks the amount that
+    /// actually crosses the pool), so a binary search over `0..=10000`
+    /// converges to the exact boundary fee.
+    /// @param reserve_in Pool reserves of the input token before the swap
+    /// @param reserve_out Pool reserves of the output token before the swap
+    /// @param amount_in Amount of the input token being swapped
+    /// @param target_impact_bps Maximum acceptable price impact, in basis points
+    /// @return The smallest fee (in basis points) that keeps impact within target
+    pub fn fee_for_target_impact(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+        target_impact_bps: U256
+    ) -> Result<u32, SolError> {
+        fee_for_impact(reserve_in, reserve_out, amount_in, target_impact_bps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_prices(ticks: &[i32]) -> Vec<U256> {
+        ticks.iter().map(|t| tick_to_sqrt_price_x96(*t).unwrap()).collect()
+    }
+
+    #[test]
+    fn flat_distribution_splits_liquidity_evenly_with_remainder_in_last_bin() {
+        let prices = sample_prices(&[-120, -60, 0, 60, 120]);
+        let distribution = liquidity_distribution(&prices, U256::from(1000), 4, SHAPE_FLAT).unwrap();
+
+        assert_eq!(distribution.len(), 4);
+        let total: U256 = distribution.iter().fold(U256::ZERO, |acc, (_, _, liquidity)| acc + *liquidity);
+        assert_eq!(total, U256::from(1000));
+        // First three bins get an equal share; only the remainder differs.
+        assert_eq!(distribution[0].2, distribution[1].2);
+        assert_eq!(distribution[1].2, distribution[2].2);
+    }
+
+    #[test]
+    fn triangular_distribution_peaks_at_center_bin() {
+        let prices = sample_prices(&[-120, -60, 0, 60, 120]);
+        let distribution =
+            liquidity_distribution(&prices, U256::from(1000), 5, SHAPE_TRIANGULAR).unwrap();
+
+        let max_liquidity = distribution.iter().map(|(_, _, l)| *l).max().unwrap();
+        let center_liquidity = distribution[distribution.len() / 2].2;
+        assert_eq!(center_liquidity, max_liquidity);
+    }
+
+    #[test]
+    fn liquidity_distribution_rejects_zero_bins_and_bad_shape() {
+        let prices = sample_prices(&[-60, 0, 60]);
+        assert!(liquidity_distribution(&prices, U256::from(1000), 0, SHAPE_FLAT).is_err());
+        assert!(liquidity_distribution(&prices, U256::from(1000), 4, 2).is_err());
+    }
+
+    #[test]
+    fn swap_simulation_applies_fee_before_pricing_impact() {
+        let (amount_out, _, impact_bps) = swap_simulation(
+            U256::from(1_000_000),
+            U256::from(1_000_000),
+            U256::from(10_000),
+            30, // 0.3%
+        )
+        .unwrap();
+
+        // 0.3% fee leaves 9_970 to cross the pool:
+        // 1_000_000 * 9_970 / 1_009_970 = 9_871 (no fee would give 9_900).
+        assert_eq!(amount_out, U256::from(9_871));
+        assert!(impact_bps > U256::ZERO);
+    }
+
+    #[test]
+    fn swap_simulation_rejects_empty_reserves_and_invalid_fee() {
+        assert!(swap_simulation(U256::ZERO, U256::from(1), U256::from(1), 30).is_err());
+        assert!(swap_simulation(U256::from(1), U256::from(1), U256::from(1), 10001).is_err());
+    }
+
+    #[test]
+    fn fee_for_impact_converges_to_a_fee_whose_impact_is_within_target() {
+        let reserve_in = U256::from(1_000_000);
+        let reserve_out = U256::from(1_000_000);
+        let amount_in = U256::from(10_000);
+
+        let fee = fee_for_impact(reserve_in, reserve_out, amount_in, U256::from(50)).unwrap();
+        let (_, _, impact) = swap_simulation(reserve_in, reserve_out, amount_in, fee).unwrap();
+
+        assert!(impact <= U256::from(50));
+    }
+}
\ No newline at end of file
@@ -3,13 +3,28 @@
 extern crate alloc;
 
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use stylus_sdk::{
-    alloy_primitives::{Address, U256},
+    alloy_primitives::{Address, U256, I256},
     alloy_sol_types::{sol, SolError},
+    call::Call,
     evm, msg,
     prelude::*,
 };
 
+#[path = "tick_math.rs"]
+mod tick_math;
+
+// Minimal interface onto a Uniswap V3-style pool's built-in TWAP oracle.
+sol_interface! {
+    interface IUniswapV3PoolOracle {
+        function observe(uint32[] calldata secondsAgos)
+            external
+            view
+            returns (int56[] memory tickCumulatives, uint160[] memory secondsPerLiquidityCumulativeX128s);
+    }
+}
+
 // Helper function to compute absolute difference between two prices
 fn abs_diff(a: U256, b: U256) -> U256 {
     if a >= b {
@@ -19,62 +34,71 @@ fn abs_diff(a: U256, b: U256) -> U256 {
     }
 }
 
-// Compute variance from a set of prices
-fn compute_variance(prices: &[U256], mean: U256) -> U256 {
-    let mut sum_squared_diff = U256::ZERO;
-    let len = U256::from(prices.len());
-    
-    if len == U256::ZERO {
-        return U256::ZERO;
+// Compute variance with Welford's single-pass algorithm: a running mean and
+// sum-of-squares (M2), built with checked arithmetic so an overflow returns
+// MathOverflow instead of silently clamping as `saturating_mul`/`_add` did.
+fn compute_variance(prices: &[U256], _mean: U256) -> Result<U256, SolError> {
+    let len = prices.len();
+
+    if len == 0 {
+        return Ok(U256::ZERO);
     }
-    
-    for price in prices {
-        let diff = if *price >= mean {
-            *price - mean
-        } else {
-            mean - *price
-        };
-        
-        // Square the difference - handle carefully to avoid overflow
-        let squared = diff.saturating_mul(diff);
-        sum_squared_diff = sum_squared_diff.saturating_add(squared);
-    }
-    
-    // Return variance (sum of squared differences divided by count)
-    sum_squared_diff / len
+
+    let mut mean = I256::ZERO;
+    let mut m2 = I256::ZERO;
+
+    for (i, price) in prices.iter().enumerate() {
+        let n = I256::try_from(i + 1).map_err(|_| MathOverflow {})?;
+        let x = I256::try_from(*price).map_err(|_| MathOverflow {})?;
+
+        let delta = x.checked_sub(mean).ok_or(MathOverflow {})?;
+        let delta_over_n = delta.checked_div(n).ok_or(MathOverflow {})?;
+        mean = mean.checked_add(delta_over_n).ok_or(MathOverflow {})?;
+
+        let delta2 = x.checked_sub(mean).ok_or(MathOverflow {})?;
+        let term = delta.checked_mul(delta2).ok_or(MathOverflow {})?;
+        m2 = m2.checked_add(term).ok_or(MathOverflow {})?;
+    }
+
+    let len = I256::try_from(len).map_err(|_| MathOverflow {})?;
+    let variance = m2.checked_div(len).ok_or(MathOverflow {})?;
+
+    U256::try_from(variance).map_err(|_| MathOverflow {}.into())
 }
 
 // Calculate mean of an array of prices
-fn compute_mean(prices: &[U256]) -> U256 {
+fn compute_mean(prices: &[U256]) -> Result<U256, SolError> {
     let mut sum = U256::ZERO;
     let len = prices.len();
-    
+
     if len == 0 {
-        return U256::ZERO;
+        return Ok(U256::ZERO);
     }
-    
+
     for price in prices {
-        sum = sum.saturating_add(*price);
+        sum = sum.checked_add(*price).ok_or(MathOverflow {})?;
     }
-    
-    sum / U256::from(len)
+
+    Ok(sum / U256::from(len))
 }
 
 // Calculate price movement intensity
-fn calculate_price_movement_intensity(prices: &[U256]) -> U256 {
+fn calculate_price_movement_intensity(prices: &[U256]) -> Result<U256, SolError> {
     if prices.len() <= 1 {
-        return U256::ZERO;
+        return Ok(U256::ZERO);
     }
-    
+
     let mut total_movement = U256::ZERO;
-    
+
     // Calculate total absolute differences between consecutive prices
     for i in 1..prices.len() {
-        total_movement = total_movement.saturating_add(abs_diff(prices[i], prices[i-1]));
+        total_movement = total_movement
+            .checked_add(abs_diff(prices[i], prices[i - 1]))
+            .ok_or(MathOverflow {})?;
     }
-    
+
     // Average movement per price point
-    total_movement / U256::from(prices.len() - 1)
+    Ok(total_movement / U256::from(prices.len() - 1))
 }
 
 // Calculate a relative volatility score based on price data
@@ -82,25 +106,25 @@ fn calculate_price_movement_intensity(prices: &[U256]) -> U256 {
 fn calculate_relative_volatility(
     prices: &[U256],
     base_price: U256,
-) -> U256 {
+) -> Result<U256, SolError> {
     // Calculate mean and variance
-    let mean = compute_mean(prices);
-    let variance = compute_variance(prices, mean);
-    
+    let mean = compute_mean(prices)?;
+    let variance = compute_variance(prices, mean)?;
+
     // Calculate movement intensity
-    let movement_intensity = calculate_price_movement_intensity(prices);
-    
+    let movement_intensity = calculate_price_movement_intensity(prices)?;
+
     // Calculate variation coefficient (variance relative to the mean)
     let variation_coefficient = if mean > U256::ZERO {
-        (variance * U256::from(10000)) / mean
+        variance.checked_mul(U256::from(10000)).ok_or(MathOverflow {})? / mean
     } else {
         U256::ZERO
     };
-    
+
     // Calculate price range as a percentage of base price
     let mut min_price = U256::MAX;
     let mut max_price = U256::ZERO;
-    
+
     for price in prices {
         if *price < min_price {
             min_price = *price;
@@ -109,37 +133,134 @@ fn calculate_relative_volatility(
             max_price = *price;
         }
     }
-    
+
     let price_range = if max_price > min_price {
         max_price - min_price
     } else {
         U256::ZERO
     };
-    
+
     let price_range_percent = if base_price > U256::ZERO {
-        (price_range * U256::from(10000)) / base_price
+        price_range.checked_mul(U256::from(10000)).ok_or(MathOverflow {})? / base_price
     } else {
         U256::ZERO
     };
-    
+
     // Compute final volatility score as a weighted sum of factors
     // Weight variance more heavily than simple range
-    let volatility_score = (variation_coefficient.saturating_mul(U256::from(6)) + 
-                           price_range_percent.saturating_mul(U256::from(3)) +
-                           movement_intensity.saturating_mul(U256::from(1))) / U256::from(10);
-    
+    let weighted_variance = variation_coefficient.checked_mul(U256::from(6)).ok_or(MathOverflow {})?;
+    let weighted_range = price_range_percent.checked_mul(U256::from(3)).ok_or(MathOverflow {})?;
+    let weighted_movement = movement_intensity.checked_mul(U256::from(1)).ok_or(MathOverflow {})?;
+
+    let weighted_sum = weighted_variance
+        .checked_add(weighted_range)
+        .ok_or(MathOverflow {})?
+        .checked_add(weighted_movement)
+        .ok_or(MathOverflow {})?;
+    let volatility_score = weighted_sum / U256::from(10);
+
     // Cap at 10000
     if volatility_score > U256::from(10000) {
-        U256::from(10000)
+        Ok(U256::from(10000))
     } else {
-        volatility_score
+        Ok(volatility_score)
+    }
+}
+
+// Helper function to compute square root
+fn sqrt(n: U256) -> U256 {
+    if n == U256::ZERO {
+        return U256::ZERO;
+    }
+
+    let mut x = n;
+    let mut y = (x + U256::from(1)) / U256::from(2);
+
+    while y < x {
+        x = y;
+        y = (x + n / x) / U256::from(2);
+    }
+
+    x
+}
+
+// Core of `calculate_ewma_volatility`, pulled out as a free function (it
+// doesn't touch contract storage) so it can be unit tested directly.
+fn ewma_volatility(recent_prices: &[U256], lambda_bps: U256) -> Result<U256, SolError> {
+    if recent_prices.len() < 2 {
+        return Err(InvalidPriceArray {}.into());
+    }
+    if lambda_bps > U256::from(10000) {
+        return Err(InvalidLambda {}.into());
+    }
+
+    let scale = U256::from(10000);
+    let one_minus_lambda = scale.checked_sub(lambda_bps).ok_or(MathOverflow {})?;
+    let mut var = U256::ZERO;
+
+    for i in 1..recent_prices.len() {
+        let prev = recent_prices[i - 1];
+        let curr = recent_prices[i];
+
+        if prev == U256::ZERO {
+            continue;
+        }
+
+        // Scaled squared return: ((curr - prev) * SCALE / prev)^2
+        let abs_return = if curr >= prev { curr - prev } else { prev - curr };
+        let scaled_return = abs_return.checked_mul(scale).ok_or(MathOverflow {})? / prev;
+        let r_squared = scaled_return.checked_mul(scaled_return).ok_or(MathOverflow {})?;
+
+        // var = (lambda * var + (SCALE - lambda) * r^2) / SCALE
+        let decayed_var = lambda_bps.checked_mul(var).ok_or(MathOverflow {})?;
+        let fresh_term = one_minus_lambda.checked_mul(r_squared).ok_or(MathOverflow {})?;
+        var = decayed_var.checked_add(fresh_term).ok_or(MathOverflow {})? / scale;
     }
+
+    let volatility = sqrt(var);
+
+    // Cap at 10000, same scale as `calculate_volatility_score`
+    Ok(if volatility > scale { scale } else { volatility })
+}
+
+// Core of `get_recommended_fee`, pulled out as a free function (it doesn't
+// touch contract storage) so it can be unit tested directly.
+fn recommended_fee(volatility_score: U256, base_fee: u32, max_fee: u32) -> u32 {
+    let base = U256::from(base_fee);
+    let max = U256::from(max_fee);
+
+    // Calculate dynamic fee based on volatility score
+    // For very low volatility (0-1000), use base fee
+    // For very high volatility (9000-10000), use max fee
+    // For values in between, scale linearly
+
+    if volatility_score <= U256::from(1000) {
+        return base_fee;
+    }
+
+    if volatility_score >= U256::from(9000) {
+        return max_fee;
+    }
+
+    // Normalized score from 0 to 8000
+    let normalized_score = volatility_score.saturating_sub(U256::from(1000));
+
+    // Calculate fee within the range
+    let fee_range = max.saturating_sub(base);
+    let fee_increase = (normalized_score.saturating_mul(fee_range)) / U256::from(8000);
+    let dynamic_fee = base.saturating_add(fee_increase);
+
+    // Convert back to u32 (safe because max_fee is a u32)
+    dynamic_fee.as_u32()
 }
 
 // Contract errors
 sol! {
     error InvalidPriceArray();
     error InvalidTimeWindow();
+    error OracleCallFailed();
+    error MathOverflow();
+    error InvalidLambda();
 }
 
 #[solidity_storage]
@@ -153,7 +274,35 @@ impl VolatilityCalculator {
     pub fn constructor(&mut self) {
         self.scaling_factor = U256::from(10000);
     }
-    
+
+    /// Read a TWAP price series straight from a pool's oracle instead of
+    /// trusting a caller-supplied price array. `seconds_ago` must be sorted
+    /// oldest-first (e.g. `[120, 90, 60, 30, 0]`); each consecutive pair
+    /// becomes one arithmetic-mean-tick price, time-weighted and therefore
+    /// far harder to manipulate than a spot price.
+    /// @param pool Address of the Uniswap V3-style pool exposing `observe`
+    /// @param seconds_ago Lookback offsets in seconds, oldest-first
+    /// @return One price per window, suitable for `calculate_volatility_score`
+    pub fn get_twap_prices(
+        &mut self,
+        pool: Address,
+        seconds_ago: Vec<u32>
+    ) -> Result<Vec<U256>, SolError> {
+        if seconds_ago.len() < 2 {
+            return Err(InvalidPriceArray {}.into());
+        }
+
+        let oracle = IUniswapV3PoolOracle::new(pool);
+        let config = Call::new_in(self);
+        let (tick_cumulatives, _) = oracle
+            .observe(config, seconds_ago.clone())
+            .map_err(|_| OracleCallFailed {})?;
+
+        let tick_cumulatives: Vec<i64> = tick_cumulatives.iter().map(|t| t.as_i64()).collect();
+        tick_math::twap_from_tick_cumulatives(&tick_cumulatives, &seconds_ago)
+            .map_err(|_| OracleCallFailed {}.into())
+    }
+
     /// Calculate a volatility score based on recent prices
     /// @param token0 Address of the first token (not used directly but included for optimization)
     /// @param token1 Address of the second token (not used directly but included for optimization)
@@ -163,7 +312,7 @@ impl VolatilityCalculator {
     pub fn calculate_volatility_score(
         &self,
         _token0: Address,
-        _token1: Address, 
+        _token1: Address,
         recent_prices: Vec<U256>,
         time_window: U256
     ) -> Result<U256, SolError> {
@@ -171,20 +320,36 @@ impl VolatilityCalculator {
         if recent_prices.len() == 0 {
             return Err(InvalidPriceArray {}.into());
         }
-        
+
         if time_window == U256::ZERO {
             return Err(InvalidTimeWindow {}.into());
         }
-        
+
         // Use the mean price as the base price for comparisons
-        let base_price = compute_mean(&recent_prices);
-        
+        let base_price = compute_mean(&recent_prices)?;
+
         // Calculate the volatility score
-        let volatility_score = calculate_relative_volatility(&recent_prices, base_price);
-        
+        let volatility_score = calculate_relative_volatility(&recent_prices, base_price)?;
+
         Ok(volatility_score)
     }
-    
+
+    /// Calculate a volatility score using a RiskMetrics-style exponentially
+    /// weighted moving average instead of a flat-window variance blend, so
+    /// fresh price moves dominate the score instead of being averaged away
+    /// by stale ones.
+    /// @param recent_prices Array of recent prices, oldest first
+    /// @param lambda_bps EWMA decay factor in basis points (0-10000); ~9400
+    ///   mirrors the RiskMetrics default of lambda = 0.94
+    /// @return Volatility score (0-10000)
+    pub fn calculate_ewma_volatility(
+        &self,
+        recent_prices: Vec<U256>,
+        lambda_bps: U256
+    ) -> Result<U256, SolError> {
+        ewma_volatility(&recent_prices, lambda_bps)
+    }
+
     /// Get a recommended fee based on volatility score
     /// @param volatility_score The volatility score (0-10000)
     /// @param base_fee The base fee to use when volatility is low
@@ -196,31 +361,77 @@ impl VolatilityCalculator {
         base_fee: u32,
         max_fee: u32
     ) -> Result<u32, SolError> {
-        let base = U256::from(base_fee);
-        let max = U256::from(max_fee);
-        
-        // Calculate dynamic fee based on volatility score
-        // For very low volatility (0-1000), use base fee
-        // For very high volatility (9000-10000), use max fee
-        // For values in between, scale linearly
-        
-        if volatility_score <= U256::from(1000) {
-            return Ok(base_fee);
-        }
-        
-        if volatility_score >= U256::from(9000) {
-            return Ok(max_fee);
-        }
-        
-        // Normalized score from 0 to 8000
-        let normalized_score = volatility_score.saturating_sub(U256::from(1000));
-        
-        // Calculate fee within the range
-        let fee_range = max.saturating_sub(base);
-        let fee_increase = (normalized_score.saturating_mul(fee_range)) / U256::from(8000);
-        let dynamic_fee = base.saturating_add(fee_increase);
-        
-        // Convert back to u32 (safe because max_fee is a u32)
-        Ok(dynamic_fee.as_u32())
-    }
-} 
\ No newline at end of file
+        Ok(recommended_fee(volatility_score, base_fee, max_fee))
+    }
+
+    /// Convenience wrapper that feeds an EWMA volatility estimate straight
+    /// into `get_recommended_fee`, so the fee reacts to fresh volatility
+    /// spikes instead of the mean/variance blend's slower response.
+    /// @param recent_prices Array of recent prices, oldest first
+    /// @param lambda_bps EWMA decay factor in basis points, see `calculate_ewma_volatility`
+    /// @param base_fee The base fee to use when volatility is low
+    /// @param max_fee The maximum fee to use when volatility is high
+    /// @return The recommended fee
+    pub fn get_recommended_fee_ewma(
+        &self,
+        recent_prices: Vec<U256>,
+        lambda_bps: U256,
+        base_fee: u32,
+        max_fee: u32
+    ) -> Result<u32, SolError> {
+        let volatility_score = self.calculate_ewma_volatility(recent_prices, lambda_bps)?;
+        self.get_recommended_fee(volatility_score, base_fee, max_fee)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variance_of_identical_prices_is_zero() {
+        let prices = [U256::from(100), U256::from(100), U256::from(100)];
+        assert_eq!(compute_variance(&prices, U256::from(100)).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn variance_detects_overflow_instead_of_saturating() {
+        // U256::MAX does not fit the signed I256 that Welford's algorithm
+        // works in, so this surfaces MathOverflow; the old
+        // saturating_mul/_add summation silently clamped here instead.
+        let prices = [U256::MAX, U256::ZERO];
+        assert!(compute_variance(&prices, U256::ZERO).is_err());
+    }
+
+    #[test]
+    fn ewma_volatility_rejects_lambda_above_one() {
+        let prices = [U256::from(100), U256::from(110)];
+        assert!(ewma_volatility(&prices, U256::from(10001)).is_err());
+    }
+
+    #[test]
+    fn ewma_volatility_is_zero_for_flat_prices() {
+        let prices = [U256::from(100), U256::from(100), U256::from(100)];
+        assert_eq!(ewma_volatility(&prices, U256::from(9400)).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn ewma_volatility_skips_zero_price_windows_without_dividing_by_zero() {
+        let prices = [U256::ZERO, U256::from(100), U256::from(110)];
+        assert!(ewma_volatility(&prices, U256::from(9400)).is_ok());
+    }
+
+    #[test]
+    fn recommended_fee_clamps_to_base_and_max_at_the_boundaries() {
+        assert_eq!(recommended_fee(U256::from(0), 10, 100), 10);
+        assert_eq!(recommended_fee(U256::from(1000), 10, 100), 10);
+        assert_eq!(recommended_fee(U256::from(9000), 10, 100), 100);
+        assert_eq!(recommended_fee(U256::from(10000), 10, 100), 100);
+    }
+
+    #[test]
+    fn recommended_fee_scales_linearly_between_thresholds() {
+        let mid = recommended_fee(U256::from(5000), 10, 100);
+        assert!(mid > 10 && mid < 100);
+    }
+}
@@ -0,0 +1,245 @@
+// Shared Q64.96 TickMath conversions, used by both PositionOptimizer and
+// VolatilityCalculator so the two contracts' tick<->sqrt-price math (and its
+// test coverage) live in exactly one place.
+extern crate alloc;
+
+use stylus_sdk::alloy_primitives::{uint, I256, U256};
+
+// Tick bounds, matching the range a Q64.96 sqrt price can represent.
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+// Bounds on the sqrt price itself, corresponding to MIN_TICK/MAX_TICK.
+const MIN_SQRT_RATIO: U256 = uint!(4295128739_U256);
+const MAX_SQRT_RATIO: U256 =
+    uint!(1461446703485210103287273052203988822378723970342_U256);
+
+// Precomputed Q128.128 constants for 1.0001^(2^i), i = 0..=19 (the same
+// magic numbers used by concentrated-liquidity AMMs' TickMath), used to
+// build the sqrt price ratio one set bit of the tick at a time. Baked in as
+// compile-time literals instead of hex strings parsed on every call.
+const TICK_RATIOS: [U256; 20] = [
+    uint!(0xfffcb933bd6fad37aa2d162d1a594001_U256),
+    uint!(0xfff97272373d413259a46990580e213a_U256),
+    uint!(0xfff2e50f5f656932ef12357cf3c7fdcc_U256),
+    uint!(0xffe5caca7e10e4e61c3624eaa0941cd0_U256),
+    uint!(0xffcb9843d60f6159c9db58835c926644_U256),
+    uint!(0xff973b41fa98c081472e6896dfb254c0_U256),
+    uint!(0xff2ea16466c96a3843ec78b326b52861_U256),
+    uint!(0xfe5dee046a99a2a811c461f1969c3053_U256),
+    uint!(0xfcbe86c7900a88aedcffc83b479aa3a4_U256),
+    uint!(0xf987a7253ac413176f2b074cf7815e54_U256),
+    uint!(0xf3392b0822b70005940c7a398e4b70f3_U256),
+    uint!(0xe7159475a2c29b7443b29c7fa6e889d9_U256),
+    uint!(0xd097f3bdfd2022b8845ad8f792aa5825_U256),
+    uint!(0xa9f746462d870fdf8a65dc1f90e061e5_U256),
+    uint!(0x70d869a156d2a1b890bb3df62baf32f7_U256),
+    uint!(0x31be135f97d08fd981231505542fcfa6_U256),
+    uint!(0x09aa508b5b7a84e1c677de54f3e99bc9_U256),
+    uint!(0x05d6af8dedb81196699c329225ee604_U256),
+    uint!(0x02216e584f5fa1ea926041bedfe98_U256),
+    uint!(0x0048a170391f7dc42444e8fa2_U256),
+];
+
+// Constants for the inverse (sqrt price -> tick) binary-logarithm method,
+// ported from Uniswap V3's `TickMath.getTickAtSqrtRatio`.
+const LOG_SQRT10001_MULT: U256 = uint!(0x3627a301d71055774c85_U256);
+const TICK_LOW_OFFSET: U256 = uint!(0x28f6481ab7f045a5af012a19d003aaa_U256);
+const TICK_HI_OFFSET: U256 = uint!(0xdb2df09e81959a81455e260799a0632f_U256);
+
+/// Error from the tick<->sqrt-price conversions. Each contract maps this to
+/// its own `sol!`-defined error at the call site.
+#[derive(Debug)]
+pub enum TickMathError {
+    TickOutOfRange,
+}
+
+/// Error from `twap_from_tick_cumulatives`. Each contract maps this to its
+/// own `sol!`-defined error at the call site.
+#[derive(Debug)]
+pub enum TwapError {
+    OracleShapeMismatch,
+    NonPositiveElapsed,
+    TickOutOfRange,
+}
+
+/// Convert a tick to its exact Q64.96 sqrt price using the fixed-point
+/// algorithm shared by concentrated-liquidity AMMs: walk the set bits of
+/// `abs(tick)`, multiplying in the precomputed ratio for each one, then
+/// invert if the tick is positive and round up from Q128.128 to Q64.96.
+pub fn tick_to_sqrt_price_x96(tick: i32) -> Result<U256, TickMathError> {
+    if tick < MIN_TICK || tick > MAX_TICK {
+        return Err(TickMathError::TickOutOfRange);
+    }
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        TICK_RATIOS[0]
+    } else {
+        U256::from(1) << 128
+    };
+
+    for (i, tick_ratio) in TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * *tick_ratio) >> 128;
+        }
+    }
+
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Downshift from Q128.128 to Q64.96, rounding up so the result never
+    // understates the true sqrt price.
+    let shifted = ratio >> 32;
+    let remainder_mask = (U256::from(1) << 32) - U256::from(1);
+    let rounded = if ratio & remainder_mask != U256::ZERO {
+        shifted + U256::from(1)
+    } else {
+        shifted
+    };
+
+    Ok(rounded)
+}
+
+/// Inverse of `tick_to_sqrt_price_x96`, ported from Uniswap V3's
+/// `TickMath.getTickAtSqrtRatio`: locate the most significant bit of the
+/// sqrt price, refine the fractional bits of its binary logarithm by
+/// repeated squaring, multiply by `log_sqrt10001` to get a bound on the
+/// tick, then pick whichever of the two candidate ticks' price actually
+/// satisfies the input — no loop over the whole tick range required.
+pub fn sqrt_price_to_tick(sqrt_price_x96: U256) -> Result<i32, TickMathError> {
+    if sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO {
+        return Err(TickMathError::TickOutOfRange);
+    }
+
+    let ratio = sqrt_price_x96 << 32;
+    let msb = 255 - ratio.leading_zeros() as i32;
+
+    let mut r = if msb >= 128 {
+        ratio >> (msb - 127) as usize
+    } else {
+        ratio << (127 - msb) as usize
+    };
+
+    let mut log_2 = (I256::try_from(msb).unwrap() - I256::try_from(128).unwrap()) << 64;
+
+    let mut shift = 63i32;
+    while shift >= 50 {
+        r = (r * r) >> 127;
+        let f = (r >> 128).as_u32();
+        log_2 = log_2 | (I256::try_from(f).unwrap() << shift);
+        r >>= f as usize;
+        shift -= 1;
+    }
+
+    let log_sqrt10001 = log_2 * I256::from_raw(LOG_SQRT10001_MULT);
+
+    let tick_low = ((log_sqrt10001 - I256::from_raw(TICK_LOW_OFFSET)) >> 128).as_i64() as i32;
+    let tick_hi = ((log_sqrt10001 + I256::from_raw(TICK_HI_OFFSET)) >> 128).as_i64() as i32;
+
+    let tick = if tick_low == tick_hi {
+        tick_low
+    } else if tick_to_sqrt_price_x96(tick_hi)? <= sqrt_price_x96 {
+        tick_hi
+    } else {
+        tick_low
+    };
+
+    Ok(tick)
+}
+
+/// Turn a consecutive pair of oracle observations into one arithmetic-mean
+/// sqrt price per window, rounding the mean tick toward negative infinity
+/// for falling-price windows (matching Uniswap's `OracleLibrary.consult`,
+/// which Rust's default truncating integer division does not do on its own).
+pub fn twap_from_tick_cumulatives(
+    tick_cumulatives: &[i64],
+    seconds_ago: &[u32],
+) -> Result<alloc::vec::Vec<U256>, TwapError> {
+    if tick_cumulatives.len() != seconds_ago.len() {
+        return Err(TwapError::OracleShapeMismatch);
+    }
+
+    let mut prices = alloc::vec::Vec::with_capacity(tick_cumulatives.len() - 1);
+    for i in 0..tick_cumulatives.len() - 1 {
+        // seconds_ago is oldest-first, so the window between observation i
+        // and i+1 spans seconds_ago[i] - seconds_ago[i+1].
+        let elapsed = seconds_ago[i] as i64 - seconds_ago[i + 1] as i64;
+        if elapsed <= 0 {
+            return Err(TwapError::NonPositiveElapsed);
+        }
+
+        let cumulative_delta = tick_cumulatives[i + 1] - tick_cumulatives[i];
+        let mut avg_tick = (cumulative_delta / elapsed) as i32;
+        if cumulative_delta < 0 && cumulative_delta % elapsed != 0 {
+            avg_tick -= 1;
+        }
+
+        let sqrt_price_x96 =
+            tick_to_sqrt_price_x96(avg_tick).map_err(|_| TwapError::TickOutOfRange)?;
+        prices.push(sqrt_price_x96);
+    }
+
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_zero_sqrt_price_is_q96_one() {
+        // At tick 0 the price is exactly 1.0, so the Q64.96 sqrt price is
+        // exactly 2^96 with no magic-constant multiplication involved.
+        let sqrt_price = tick_to_sqrt_price_x96(0).unwrap();
+        assert_eq!(sqrt_price, U256::from(1) << 96);
+    }
+
+    #[test]
+    fn tick_to_sqrt_price_round_trips() {
+        for tick in [MIN_TICK, -100000, -60, -1, 0, 1, 60, 100000, MAX_TICK] {
+            let sqrt_price = tick_to_sqrt_price_x96(tick).unwrap();
+            assert_eq!(sqrt_price_to_tick(sqrt_price).unwrap(), tick);
+        }
+    }
+
+    #[test]
+    fn sqrt_price_is_monotonic_in_tick() {
+        let lower = tick_to_sqrt_price_x96(100).unwrap();
+        let upper = tick_to_sqrt_price_x96(101).unwrap();
+        assert!(upper > lower);
+    }
+
+    #[test]
+    fn tick_out_of_range_is_rejected() {
+        assert!(tick_to_sqrt_price_x96(MIN_TICK - 1).is_err());
+        assert!(tick_to_sqrt_price_x96(MAX_TICK + 1).is_err());
+    }
+
+    #[test]
+    fn sqrt_price_out_of_range_is_rejected() {
+        assert!(sqrt_price_to_tick(MIN_SQRT_RATIO - U256::from(1)).is_err());
+        assert!(sqrt_price_to_tick(MAX_SQRT_RATIO).is_err());
+    }
+
+    #[test]
+    fn twap_rounds_negative_mean_toward_negative_infinity() {
+        // delta = -7, elapsed = 2 => exact mean is -3.5, which should floor
+        // to -4, not truncate toward zero to -3.
+        let tick_cumulatives = [0i64, -7];
+        let seconds_ago = [2u32, 0];
+        let prices = twap_from_tick_cumulatives(&tick_cumulatives, &seconds_ago).unwrap();
+        assert_eq!(prices[0], tick_to_sqrt_price_x96(-4).unwrap());
+    }
+
+    #[test]
+    fn twap_rejects_mismatched_oracle_shape() {
+        let tick_cumulatives = [0i64, 10, 20];
+        let seconds_ago = [2u32, 1];
+        assert!(matches!(
+            twap_from_tick_cumulatives(&tick_cumulatives, &seconds_ago),
+            Err(TwapError::OracleShapeMismatch)
+        ));
+    }
+}